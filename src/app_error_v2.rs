@@ -1,17 +1,112 @@
 use axum::{
-    http::StatusCode,
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
     response::{IntoResponse, Response},
+    Json,
 };
+use serde::Serialize;
 use tracing::error;
 
+tokio::task_local! {
+    /// Whether the current request's `Accept` header mentions `application/json`, set by
+    /// [`negotiate_error_format`] so [`AppError::into_response`] can pick a matching body.
+    static ACCEPTS_JSON: bool;
+}
+
+/// Breaking change: this used to be a public tuple struct
+/// (`AppError(pub StatusCode, pub anyhow::Error)`). Code that constructed it directly — rather
+/// than via `?`/`From` — must migrate to `AppError::new(status, err)`.
 #[derive(Debug)]
-pub struct AppError(pub StatusCode, pub anyhow::Error);
+pub struct AppError {
+    status: StatusCode,
+    error: anyhow::Error,
+    type_uri: Option<String>,
+    detail: Option<String>,
+}
+
+impl AppError {
+    /// Create a new `AppError` with an explicit status code
+    pub fn new(status: StatusCode, error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            status,
+            error: error.into(),
+            type_uri: None,
+            detail: None,
+        }
+    }
+
+    /// Set the `type` URI reported in the RFC 7807 problem detail body. Defaults to
+    /// `"about:blank"` when not set.
+    pub fn with_type(mut self, uri: impl Into<String>) -> Self {
+        self.type_uri = Some(uri.into());
+        return self;
+    }
+
+    /// Override the `detail` message reported in the response body. Defaults to the
+    /// wrapped `anyhow` error rendered with its full context chain.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        return self;
+    }
+
+    fn detail(&self) -> String {
+        self.detail
+            .clone()
+            .unwrap_or_else(|| format!("{:#}", self.error))
+    }
+}
+
+/// RFC 7807 ("Problem Details for HTTP APIs") error body
+#[derive(Debug, Serialize)]
+struct ProblemDetail {
+    r#type: String,
+    title: String,
+    status: u16,
+    detail: String,
+}
+
+/// Canonical reason phrase for a status code, used as the problem+json `title`. The plain-text
+/// body prints the status code's `Display` form instead, which carries the same reason phrase.
+fn title_for_status(status: StatusCode) -> String {
+    status
+        .canonical_reason()
+        .map(str::to_string)
+        .unwrap_or_else(|| status.to_string())
+}
 
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        error!("Error: {:#?}", self.1);
-        (self.0, format!("{}: {:#}", self.0, self.1)).into_response()
+        error!("Error: {:#?}", self.error);
+
+        let wants_json = ACCEPTS_JSON.try_with(|accepts| *accepts).unwrap_or(false);
+        if wants_json {
+            let problem = ProblemDetail {
+                r#type: self
+                    .type_uri
+                    .clone()
+                    .unwrap_or_else(|| "about:blank".to_string()),
+                title: title_for_status(self.status),
+                status: self.status.as_u16(),
+                detail: self.detail(),
+            };
+            // `Json` alone would send `Content-Type: application/json`; RFC 7807 requires the
+            // more specific `application/problem+json` so clients can tell a problem body apart
+            // from an ordinary JSON payload.
+            return (
+                self.status,
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/problem+json"),
+                )],
+                Json(problem),
+            )
+                .into_response();
+        }
+
+        let status = self.status;
+        (status, format!("{}: {}", status, self.detail())).into_response()
     }
 }
 // This enables using `?` on functions that return `Result<_, anyhow::Error>` to turn them into
@@ -21,6 +116,20 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self(StatusCode::INTERNAL_SERVER_ERROR, err.into())
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, err)
     }
 }
+
+/// Middleware that records whether the incoming request's `Accept` header mentions
+/// `application/json` (anywhere in the header, whatever its quality value), so an `AppError` returned further down the stack renders as an
+/// RFC 7807 problem detail instead of the plain-text fallback.
+pub async fn negotiate_error_format(request: Request, next: Next) -> Response {
+    let wants_json = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|accept| accept.contains("application/json"))
+        .unwrap_or(false);
+
+    ACCEPTS_JSON.scope(wants_json, next.run(request)).await
+}
@@ -1,19 +1,28 @@
-use std::io::Write;
+use std::{io::Write, time::Duration};
 
 use anyhow::{Context, Result};
 use axum::{
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    body::Body,
+    error_handling::HandleErrorLayer,
+    extract::Request,
+    http::{HeaderName, HeaderValue, Method, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
-use tokio::{fs::File, io::AsyncReadExt, net::TcpListener, signal};
-use tower::ServiceBuilder;
+use tokio::{net::TcpListener, signal};
+use tower::{
+    timeout::{error::Elapsed, TimeoutLayer},
+    BoxError, ServiceBuilder,
+};
 use tower_http::{
+    compression::CompressionLayer,
     cors::{Any, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
     trace::{self, TraceLayer},
 };
-use tracing::Level;
+use tracing::{Level, Span};
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 use utoipa::{OpenApi, ToSchema};
@@ -65,82 +74,312 @@ pub fn default_router() -> Router {
     return Router::new().layer(tracing).layer(cors);
 }
 
+/// Output format for [`init_tracing_subscriber_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable output in `tracing_subscriber`'s default format, one line per event. Good
+    /// for local development.
+    Pretty,
+    /// Single-line, human-readable output.
+    Compact,
+    /// Line-delimited JSON. Good for ingestion by log aggregators.
+    Json,
+}
+
+/// Initializes tracing subscriber with the given log format and env filter layer
+///
+/// * `format`: how log lines are rendered
+/// * `default_filter`: the `EnvFilter` directive to fall back to when `RUST_LOG` isn't set
+pub fn init_tracing_subscriber_with(format: LogFormat, default_filter: &str) -> Result<()> {
+    let filter_layer =
+        EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(default_filter))?;
+    let registry = tracing_subscriber::registry().with(filter_layer);
+
+    match format {
+        LogFormat::Pretty => registry.with(fmt::layer().with_target(false)).init(),
+        LogFormat::Compact => registry
+            .with(fmt::layer().with_target(false).compact())
+            .init(),
+        LogFormat::Json => registry.with(fmt::layer().json()).init(),
+    }
+
+    return Ok(());
+}
+
 /// Initializes tracing subscriber with format and env filter layer
 pub fn init_tracing_subcriber() -> Result<()> {
-    let fmt_layer = fmt::layer().with_target(false);
-    let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
+    return init_tracing_subscriber_with(LogFormat::Pretty, "info");
+}
 
-    tracing_subscriber::registry()
-        .with(filter_layer)
-        .with(fmt_layer)
-        .init();
+/// Which origins a [`CorsConfig`] allows
+#[derive(Debug, Clone)]
+pub enum AllowedOrigins {
+    /// Allow every origin (`Access-Control-Allow-Origin: *`). Cannot be combined
+    /// with `allow_credentials: true` - the CORS spec forbids `*` origin with credentials.
+    Any,
+    /// An explicit allow-list of origins, e.g. `"https://example.com".parse().unwrap()`
+    List(Vec<HeaderValue>),
+}
 
-    return Ok(());
+/// Configuration for the CORS layer built by [`attach_middleware`]
+///
+/// * `allowed_origins`: the origins to allow
+/// * `allowed_methods`: the HTTP methods to allow
+/// * `allowed_headers`: the request headers to allow
+/// * `allow_credentials`: whether to send `Access-Control-Allow-Credentials: true`. Rejected by
+///   [`attach_middleware`] with an error (not a panic) when combined with `AllowedOrigins::Any`,
+///   since the CORS spec forbids a `*` origin with credentials.
+/// * `max_age`: how long browsers may cache a preflight response
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    pub allowed_origins: AllowedOrigins,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+    pub allow_credentials: bool,
+    pub max_age: Option<Duration>,
 }
 
-/// Attach tracing and cors middleware to a router
+impl CorsConfig {
+    /// The permissive config `attach_tracing_cors_middleware` used to hardcode: any origin, no
+    /// credentials, `GET`/`POST`/`OPTIONS`, and `Content-Type`/`Authorization` headers
+    pub fn permissive() -> Self {
+        Self {
+            allowed_origins: AllowedOrigins::Any,
+            allowed_methods: vec![Method::GET, Method::POST, Method::OPTIONS],
+            allowed_headers: vec![
+                axum::http::header::CONTENT_TYPE,
+                axum::http::header::AUTHORIZATION,
+            ],
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self::permissive()
+    }
+}
+
+fn build_cors_layer(config: CorsConfig) -> Result<CorsLayer> {
+    if config.allow_credentials && matches!(config.allowed_origins, AllowedOrigins::Any) {
+        anyhow::bail!(
+            "CorsConfig: allow_credentials cannot be combined with AllowedOrigins::Any - the \
+             CORS spec forbids a `*` origin with credentials; use AllowedOrigins::List instead"
+        );
+    }
+
+    let mut cors = match config.allowed_origins {
+        AllowedOrigins::Any => CorsLayer::new().allow_origin(Any),
+        AllowedOrigins::List(origins) => CorsLayer::new().allow_origin(origins),
+    };
+
+    cors = cors
+        .allow_methods(config.allowed_methods)
+        .allow_headers(config.allowed_headers)
+        .allow_credentials(config.allow_credentials);
+
+    if let Some(max_age) = config.max_age {
+        cors = cors.max_age(max_age);
+    }
+
+    return Ok(cors);
+}
+
+/// Header that carries the request id injected by [`attach_middleware`]
+fn request_id_header() -> HeaderName {
+    HeaderName::from_static("x-request-id")
+}
+
+/// Builds the tracing span for a request, recording the `x-request-id` set by
+/// `SetRequestIdLayer` so every log line for a request is correlatable
+fn make_request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %request_id,
+    )
+}
+
+/// Attach tracing and cors middleware to a router, with the cors layer built from `cors_config`
+///
+/// A `timeout`, if given, is layered *inside* the tracing/cors/error-negotiation stack (closer
+/// to the router), not on top of it — `negotiate_error_format`'s accept-header scope has to
+/// still be active when a timed-out request's `AppError` is turned into a response, otherwise
+/// it always renders as plain text regardless of what the client asked for. Use this instead of
+/// layering [`attach_timeout`] on top of an already-`attach_middleware`d router.
+///
+/// Returns an error instead of building the router if `cors_config` combines
+/// `allow_credentials: true` with `AllowedOrigins::Any`, which the CORS spec forbids - surface
+/// this as a startup error rather than a panic deep inside `tower_http`.
 ///
 /// * `router`: the router to attach middleware to
-pub fn attach_tracing_cors_middleware(router: Router) -> Router {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_headers([
-            axum::http::header::CONTENT_TYPE,
-            axum::http::header::AUTHORIZATION,
-        ])
-        .allow_methods([
-            axum::http::Method::GET,
-            axum::http::Method::POST,
-            axum::http::Method::OPTIONS,
-        ]);
+/// * `cors_config`: the CORS policy to enforce
+/// * `timeout`: how long a request may run before it is aborted with a `408`, if any
+pub fn attach_middleware(
+    router: Router,
+    cors_config: CorsConfig,
+    timeout: Option<Duration>,
+) -> Result<Router> {
+    let cors = build_cors_layer(cors_config)?;
+    let request_id_header = request_id_header();
 
     let tracing = TraceLayer::new_for_http()
-        .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+        .make_span_with(make_request_span)
         .on_response(trace::DefaultOnResponse::new().level(Level::INFO));
 
-    return router.layer(ServiceBuilder::new().layer(tracing).layer(cors));
-    // return router.layer(tracing).layer(cors);
+    let router = match timeout {
+        Some(timeout) => attach_timeout(router, timeout),
+        None => router,
+    };
+
+    return Ok(router.layer(
+        ServiceBuilder::new()
+            .layer(SetRequestIdLayer::new(
+                request_id_header.clone(),
+                MakeRequestUuid,
+            ))
+            .layer(tracing)
+            .layer(PropagateRequestIdLayer::new(request_id_header))
+            .layer(cors)
+            .layer(axum::middleware::from_fn(
+                app_error_v2::negotiate_error_format,
+            )),
+    ));
+}
+
+/// Attach tracing and cors middleware to a router
+///
+/// * `router`: the router to attach middleware to
+pub fn attach_tracing_cors_middleware(router: Router) -> Router {
+    return attach_middleware(router, CorsConfig::permissive(), None).expect(
+        "CorsConfig::permissive() never combines allow_credentials with AllowedOrigins::Any",
+    );
+}
+
+/// Attach gzip/brotli/zstd response compression to a router
+///
+/// * `router`: the router to attach the compression layer to
+pub fn attach_compression(router: Router) -> Router {
+    return router.layer(CompressionLayer::new());
+}
+
+/// Map a timed-out request (or any other error `TimeoutLayer` lets through) to an `AppError`,
+/// so it renders through the same plain-text/problem+json negotiation as other errors
+async fn handle_timeout_error(err: BoxError) -> app_error_v2::AppError {
+    if err.is::<Elapsed>() {
+        return app_error_v2::AppError::new(
+            StatusCode::REQUEST_TIMEOUT,
+            anyhow::anyhow!("request timed out"),
+        );
+    }
+
+    return app_error_v2::AppError::new(StatusCode::INTERNAL_SERVER_ERROR, anyhow::anyhow!(err));
+}
+
+/// Attach a request timeout to a router. Requests that run longer than `timeout` are aborted
+/// and receive a `408 Request Timeout`.
+///
+/// Must be layered *before* [`attach_middleware`]/[`attach_tracing_cors_middleware`] (i.e. call
+/// this first and pass its result to them), not after - the timeout layer drops the
+/// timed-out future, so if this wraps an already-`attach_middleware`d router, the timeout fires
+/// outside `negotiate_error_format`'s accept-header scope and the `408` always renders as plain
+/// text. Prefer passing `timeout` to [`attach_middleware`] directly, which gets this right for you.
+///
+/// * `router`: the router to attach the timeout layer to
+/// * `timeout`: how long a request may run before it is aborted
+pub fn attach_timeout(router: Router, timeout: Duration) -> Router {
+    return router.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(handle_timeout_error))
+            .layer(TimeoutLayer::new(timeout)),
+    );
 }
 
+/// Version and build metadata for a service, returned by [`app_version`]
+///
+/// Build the calling crate with [`home_response!`] rather than constructing this directly so
+/// `version` reflects that crate's own `Cargo.toml`, not `common-axum`'s.
 #[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub struct HomeResponse {
     pub version: String,
+    /// The git commit the binary was built from, when `GIT_COMMIT_HASH` was set at build time
+    pub git_commit: Option<String>,
+    /// When the binary was built, when `BUILD_TIMESTAMP` was set at build time
+    pub build_timestamp: Option<String>,
+    /// The `rustc` version used to build the binary, when `RUSTC_VERSION` was set at build time
+    pub rustc_version: Option<String>,
 }
 
-#[utoipa::path(
-    get,
-    path = "/",
-    responses(
-        (status = 200, description = "Version of the server", body = HomeResponse),
-        (status = 500, description = "Failed to get the vesion of the server", body = String),
-    )
-)]
-pub async fn app_version() -> Result<Json<HomeResponse>, app_error_v2::AppError> {
-    /// Simplified `Cargo.toml` structure
-    #[derive(Deserialize)]
-    struct CargoToml {
-        pub package: PackageKeys,
-    }
-
-    #[derive(Deserialize)]
-    struct PackageKeys {
-        // pub name: String,
-        pub version: String,
+impl HomeResponse {
+    /// Construct a `HomeResponse` from a compile-time version and optional build metadata.
+    /// Prefer the [`home_response!`] macro, which fills this in for the calling crate.
+    pub fn new(
+        version: impl Into<String>,
+        git_commit: Option<&str>,
+        build_timestamp: Option<&str>,
+        rustc_version: Option<&str>,
+    ) -> Self {
+        Self {
+            version: version.into(),
+            git_commit: git_commit.map(str::to_string),
+            build_timestamp: build_timestamp.map(str::to_string),
+            rustc_version: rustc_version.map(str::to_string),
+        }
     }
+}
 
-    let mut file = File::open("Cargo.toml")
-        .await
-        .context("Failed to open Cargo.toml")?;
-    let mut file_contents: String = Default::default();
-    file.read_to_string(&mut file_contents)
-        .await
-        .context("Failed to read Cargo.toml")?;
-    let cargo_toml = toml::from_str::<CargoToml>(file_contents.as_str())
-        .context("Failed to parse Cargo.toml")?;
+/// Build a [`HomeResponse`] for the calling crate.
+///
+/// `version` is captured at compile time via `env!("CARGO_PKG_VERSION")`, so downstream crates
+/// that invoke this macro get their own package's version, not `common-axum`'s. Build metadata
+/// is read from `GIT_COMMIT_HASH`, `BUILD_TIMESTAMP`, and `RUSTC_VERSION` environment variables
+/// when a `build.rs` sets them via `cargo:rustc-env`, and is `None` otherwise.
+#[macro_export]
+macro_rules! home_response {
+    () => {
+        $crate::axum::HomeResponse::new(
+            env!("CARGO_PKG_VERSION"),
+            option_env!("GIT_COMMIT_HASH"),
+            option_env!("BUILD_TIMESTAMP"),
+            option_env!("RUSTC_VERSION"),
+        )
+    };
+}
 
-    return Ok(Json(HomeResponse {
-        version: cargo_toml.package.version,
-    }));
+/// Define an `app_version` handler bound to the *calling* crate's own version and build
+/// metadata, suitable for mounting as a service's `/` route (e.g. `.route("/", get(app_version))`
+/// after expanding `common_axum::app_version!();` at module scope).
+///
+/// This has to be a macro, not a plain function, because `env!`/`option_env!` inside
+/// [`home_response!`] are resolved at the *invocation site's* compilation - a handler defined
+/// once inside `common-axum` itself would be permanently bound to `common-axum`'s own version,
+/// not the consuming service's.
+#[macro_export]
+macro_rules! app_version {
+    () => {
+        #[utoipa::path(
+            get,
+            path = "/",
+            responses(
+                (status = 200, description = "Version of the server", body = $crate::axum::HomeResponse),
+                (status = 500, description = "Failed to get the vesion of the server", body = String),
+            )
+        )]
+        pub async fn app_version(
+        ) -> ::std::result::Result<::axum::Json<$crate::axum::HomeResponse>, $crate::app_error_v2::AppError>
+        {
+            Ok(::axum::Json($crate::home_response!()))
+        }
+    };
 }
 
 /// Start axum server on a specific port
@@ -210,3 +449,41 @@ pub fn generate_open_api_spec_from_open_api(
         .context("Failed to write open api spec to file")?;
     return Ok(());
 }
+
+/// Self-contained Swagger UI page template, with `{{SPEC_URL}}` swapped for the path the spec
+/// is served from
+const SWAGGER_UI_HTML: &str = include_str!("swagger_ui.html");
+
+fn swagger_ui_html(spec_url: &str) -> Html<String> {
+    Html(SWAGGER_UI_HTML.replace("{{SPEC_URL}}", spec_url))
+}
+
+/// Mount a live OpenAPI JSON endpoint and a Swagger UI page onto a router
+///
+/// * `router`: the router to mount the routes onto
+/// * `json_path`: path to serve the OpenAPI spec as JSON, e.g. `/api-docs/openapi.json`
+/// * `ui_path`: path to serve the Swagger UI page, e.g. `/swagger-ui`
+pub fn mount_openapi<T: OpenApi>(router: Router, json_path: &str, ui_path: &str) -> Router {
+    return mount_openapi_spec(router, T::openapi(), json_path, ui_path);
+}
+
+/// Mount a live OpenAPI JSON endpoint and a Swagger UI page onto a router, from an already
+/// constructed [`utoipa::openapi::OpenApi`] value (e.g. one assembled at runtime from several
+/// merged specs)
+///
+/// * `router`: the router to mount the routes onto
+/// * `spec`: the OpenAPI spec to serve
+/// * `json_path`: path to serve the OpenAPI spec as JSON, e.g. `/api-docs/openapi.json`
+/// * `ui_path`: path to serve the Swagger UI page, e.g. `/swagger-ui`
+pub fn mount_openapi_spec(
+    router: Router,
+    spec: utoipa::openapi::OpenApi,
+    json_path: &str,
+    ui_path: &str,
+) -> Router {
+    let ui_html = swagger_ui_html(json_path);
+
+    return router
+        .route(json_path, get(move || async move { Json(spec) }))
+        .route(ui_path, get(move || async move { ui_html }));
+}